@@ -0,0 +1,253 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::prom::{FmtLabels, FmtMetric, MAX_PRECISE_VALUE};
+
+/// The upper bound ("le") of each non-overflow bucket in a `Histogram`, in
+/// the same unit as observed values.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds(pub &'static [f64]);
+
+/// The distributed-trace (or span) id to attach to an observation as an
+/// OpenMetrics exemplar, if the observation is part of a trace.
+#[derive(Clone, Debug, Default)]
+pub struct ExemplarContext(Option<String>);
+
+impl ExemplarContext {
+    pub fn new(trace_id: impl Into<String>) -> Self {
+        ExemplarContext(Some(trace_id.into()))
+    }
+
+    pub fn none() -> Self {
+        ExemplarContext(None)
+    }
+}
+
+/// The most recent observation recorded into a single bucket, recorded
+/// for OpenMetrics exemplars and overwritten on each later observation
+/// into that bucket.
+#[derive(Clone, Debug)]
+struct Exemplar {
+    trace_id: String,
+    value: f64,
+    timestamp: Duration,
+}
+
+struct Bucket {
+    count: AtomicUsize,
+    exemplar: Mutex<Option<Exemplar>>,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Bucket {
+            count: AtomicUsize::new(0),
+            exemplar: Mutex::new(None),
+        }
+    }
+}
+
+/// A Prometheus `histogram`, with an extra overflow ("+Inf") bucket and,
+/// per bucket, a single-slot ring buffer recording the most recent
+/// `(trace_id, value, timestamp)` observed into it. That ring buffer
+/// backs the OpenMetrics exemplar emitted on each bucket line; it's
+/// silently ignored when formatting classic Prometheus text.
+pub struct Histogram {
+    bounds: Bounds,
+    buckets: Box<[Bucket]>,
+    sum: Mutex<f64>,
+    count: AtomicUsize,
+}
+
+impl Histogram {
+    pub fn new(bounds: Bounds) -> Self {
+        let buckets = (0..=bounds.0.len()).map(|_| Bucket::default()).collect();
+        Histogram {
+            bounds,
+            buckets,
+            sum: Mutex::new(0.0),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records an observed `value`, attaching `exemplar` to the bucket it
+    /// falls into.
+    pub fn observe(&self, value: f64, exemplar: ExemplarContext) {
+        let value = if value > MAX_PRECISE_VALUE as f64 {
+            MAX_PRECISE_VALUE as f64
+        } else {
+            value
+        };
+
+        let idx = self
+            .bounds
+            .0
+            .iter()
+            .position(|&le| value <= le)
+            .unwrap_or_else(|| self.bounds.0.len());
+        let bucket = &self.buckets[idx];
+        bucket.count.fetch_add(1, Ordering::Relaxed);
+        if let Some(trace_id) = exemplar.0 {
+            let mut slot = bucket.exemplar.lock().expect("histogram exemplar lock poisoned");
+            *slot = Some(Exemplar {
+                trace_id,
+                value,
+                timestamp: unix_now(),
+            });
+        }
+
+        *self.sum.lock().expect("histogram sum lock poisoned") += value;
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn fmt_exemplar(f: &mut fmt::Formatter<'_>, bucket: &Bucket) -> fmt::Result {
+        let exemplar = bucket.exemplar.lock().expect("histogram exemplar lock poisoned");
+        if let Some(ref e) = *exemplar {
+            write!(
+                f,
+                " # {{trace_id=\"{}\"}} {} {}",
+                e.trace_id,
+                e.value,
+                e.timestamp.as_secs() as f64 + f64::from(e.timestamp.subsec_nanos()) / 1e9
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+impl FmtMetric for Histogram {
+    const KIND: &'static str = "histogram";
+
+    fn fmt_metric<N: fmt::Display>(&self, f: &mut fmt::Formatter<'_>, name: N) -> fmt::Result {
+        let mut cumulative = 0;
+        for (le, bucket) in self.bounds.0.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.count.load(Ordering::Relaxed);
+            writeln!(f, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative)?;
+        }
+        let total = self.count.load(Ordering::Relaxed);
+        writeln!(f, "{}_bucket{{le=\"+Inf\"}} {}", name, total)?;
+        writeln!(
+            f,
+            "{}_sum {}",
+            name,
+            *self.sum.lock().expect("histogram sum lock poisoned")
+        )?;
+        writeln!(f, "{}_count {}", name, total)?;
+
+        Ok(())
+    }
+
+    fn fmt_metric_labeled<N, L>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: N,
+        labels: L,
+    ) -> fmt::Result
+    where
+        N: fmt::Display,
+        L: FmtLabels,
+    {
+        let mut cumulative = 0;
+        for (le, bucket) in self.bounds.0.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.count.load(Ordering::Relaxed);
+            write!(f, "{}_bucket{{", name)?;
+            labels.fmt_labels(f)?;
+            writeln!(f, ",le=\"{}\"}} {}", le, cumulative)?;
+        }
+        let total = self.count.load(Ordering::Relaxed);
+        write!(f, "{}_bucket{{", name)?;
+        labels.fmt_labels(f)?;
+        writeln!(f, ",le=\"+Inf\"}} {}", total)?;
+
+        write!(f, "{}_sum{{", name)?;
+        labels.fmt_labels(f)?;
+        writeln!(
+            f,
+            "}} {}",
+            *self.sum.lock().expect("histogram sum lock poisoned")
+        )?;
+
+        write!(f, "{}_count{{", name)?;
+        labels.fmt_labels(f)?;
+        writeln!(f, "}} {}", total)?;
+
+        Ok(())
+    }
+
+    fn fmt_metric_exemplar<N: fmt::Display>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: N,
+    ) -> fmt::Result {
+        let mut cumulative = 0;
+        for (le, bucket) in self.bounds.0.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.count.load(Ordering::Relaxed);
+            write!(f, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative)?;
+            Self::fmt_exemplar(f, bucket)?;
+            writeln!(f)?;
+        }
+        let total = self.count.load(Ordering::Relaxed);
+        write!(f, "{}_bucket{{le=\"+Inf\"}} {}", name, total)?;
+        Self::fmt_exemplar(f, &self.buckets[self.bounds.0.len()])?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{}_sum {}",
+            name,
+            *self.sum.lock().expect("histogram sum lock poisoned")
+        )?;
+        writeln!(f, "{}_count {}", name, total)?;
+
+        Ok(())
+    }
+
+    fn fmt_metric_labeled_exemplar<N, L>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: N,
+        labels: L,
+    ) -> fmt::Result
+    where
+        N: fmt::Display,
+        L: FmtLabels,
+    {
+        let mut cumulative = 0;
+        for (le, bucket) in self.bounds.0.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.count.load(Ordering::Relaxed);
+            write!(f, "{}_bucket{{", name)?;
+            labels.fmt_labels(f)?;
+            write!(f, ",le=\"{}\"}} {}", le, cumulative)?;
+            Self::fmt_exemplar(f, bucket)?;
+            writeln!(f)?;
+        }
+        let total = self.count.load(Ordering::Relaxed);
+        write!(f, "{}_bucket{{", name)?;
+        labels.fmt_labels(f)?;
+        write!(f, ",le=\"+Inf\"}} {}", total)?;
+        Self::fmt_exemplar(f, &self.buckets[self.bounds.0.len()])?;
+        writeln!(f)?;
+
+        write!(f, "{}_sum{{", name)?;
+        labels.fmt_labels(f)?;
+        writeln!(
+            f,
+            "}} {}",
+            *self.sum.lock().expect("histogram sum lock poisoned")
+        )?;
+
+        write!(f, "{}_count{{", name)?;
+        labels.fmt_labels(f)?;
+        writeln!(f, "}} {}", total)?;
+
+        Ok(())
+    }
+}
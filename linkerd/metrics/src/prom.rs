@@ -10,7 +10,13 @@ pub(crate) const MAX_PRECISE_VALUE: u64 = 0x20_0000_0000_0000;
 
 /// Writes a block of metrics in prometheus-formatted output.
 pub trait FmtMetrics {
-    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+    /// Writes this block of metrics in the given `ExpositionFormat`.
+    ///
+    /// Implementors that hold `Histogram`/`Counter` scopes should format
+    /// them through `Metric::fmt_scopes_in`, which picks between the
+    /// classic path and the exemplar-carrying OpenMetrics path based on
+    /// `format`; a classic-only implementor can ignore `format` entirely.
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>, format: ExpositionFormat) -> fmt::Result;
 
     fn as_display(&self) -> DisplayMetrics<&Self>
     where
@@ -36,7 +42,7 @@ pub struct AndThen<A, B>(A, B);
 
 impl<F: FmtMetrics> fmt::Display for DisplayMetrics<F> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt_metrics(f)
+        self.0.fmt_metrics(f, ExpositionFormat::Prometheus)
     }
 }
 
@@ -67,6 +73,37 @@ pub trait FmtMetric {
     where
         N: fmt::Display,
         L: FmtLabels;
+
+    /// Writes a metric with the given name and no labels, as
+    /// `fmt_metric` does, but in OpenMetrics text, which additionally
+    /// permits attaching an exemplar to a counter's total.
+    ///
+    /// The default implementation just delegates to `fmt_metric` and
+    /// emits no exemplar, which is correct both for classic Prometheus
+    /// output and for any metric type OpenMetrics doesn't allow exemplars
+    /// on. Only `Histogram` (on its bucket lines) overrides this.
+    fn fmt_metric_exemplar<N: fmt::Display>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: N,
+    ) -> fmt::Result {
+        self.fmt_metric(f, name)
+    }
+
+    /// The OpenMetrics counterpart to `fmt_metric_labeled`; see
+    /// `fmt_metric_exemplar`.
+    fn fmt_metric_labeled_exemplar<N, L>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: N,
+        labels: L,
+    ) -> fmt::Result
+    where
+        N: fmt::Display,
+        L: FmtLabels,
+    {
+        self.fmt_metric_labeled(f, name, labels)
+    }
 }
 
 /// Describes a metric statically.
@@ -119,6 +156,52 @@ impl<'a, N: fmt::Display, M: FmtMetric> Metric<'a, N, M> {
 
         Ok(())
     }
+
+    /// The OpenMetrics counterpart to `fmt_scopes`: formats each scope with
+    /// any exemplars it carries attached.
+    pub fn fmt_scopes_exemplars<'s, L, S: 's, I, F>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        scopes: I,
+        to_metric: F,
+    ) -> fmt::Result
+    where
+        L: FmtLabels,
+        I: IntoIterator<Item = (L, &'s S)>,
+        F: Fn(&S) -> &M,
+    {
+        for (labels, scope) in scopes {
+            to_metric(scope).fmt_metric_labeled_exemplar(f, &self.name, labels)?;
+        }
+
+        Ok(())
+    }
+
+    /// Formats a single metric across labeled scopes in the given
+    /// `ExpositionFormat`, dispatching to `fmt_scopes` for classic
+    /// Prometheus text and `fmt_scopes_exemplars` for OpenMetrics.
+    ///
+    /// `FmtMetrics` implementors should format their scoped metrics
+    /// through this method rather than calling `fmt_scopes` directly, so
+    /// that the negotiated format actually reaches each metric's
+    /// exemplars instead of always taking the classic path.
+    pub fn fmt_scopes_in<'s, L, S: 's, I, F>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        format: ExpositionFormat,
+        scopes: I,
+        to_metric: F,
+    ) -> fmt::Result
+    where
+        L: FmtLabels,
+        I: IntoIterator<Item = (L, &'s S)>,
+        F: Fn(&S) -> &M,
+    {
+        match format {
+            ExpositionFormat::Prometheus => self.fmt_scopes(f, scopes, to_metric),
+            ExpositionFormat::OpenMetrics => self.fmt_scopes_exemplars(f, scopes, to_metric),
+        }
+    }
 }
 
 // ===== impl FmtLabels =====
@@ -166,22 +249,97 @@ impl<A: FmtLabels, B: FmtLabels> FmtLabels for (Option<A>, B) {
 // ===== impl FmtMetrics =====
 
 impl<'a, A: FmtMetrics + 'a> FmtMetrics for &'a A {
-    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        (*self).fmt_metrics(f)
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>, format: ExpositionFormat) -> fmt::Result {
+        (*self).fmt_metrics(f, format)
     }
 }
 
 impl<A: FmtMetrics, B: FmtMetrics> FmtMetrics for AndThen<A, B> {
-    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt_metrics(f)?;
-        self.1.fmt_metrics(f)?;
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>, format: ExpositionFormat) -> fmt::Result {
+        self.0.fmt_metrics(f, format)?;
+        self.1.fmt_metrics(f, format)?;
 
         Ok(())
     }
 }
 
 impl FmtMetrics for () {
-    fn fmt_metrics(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn fmt_metrics(&self, _: &mut fmt::Formatter<'_>, _: ExpositionFormat) -> fmt::Result {
+        Ok(())
+    }
+}
+
+// ===== impl ExpositionFormat =====
+
+/// The exposition format negotiated for a metrics scrape.
+///
+/// OpenMetrics is a superset of the classic Prometheus text format: it
+/// additionally permits exemplars on counter totals and histogram bucket
+/// lines, and requires the document to end with a `# EOF` line.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ExpositionFormat {
+    Prometheus,
+    OpenMetrics,
+}
+
+impl ExpositionFormat {
+    const OPENMETRICS_MEDIA_TYPE: &'static str = "application/openmetrics-text";
+
+    /// Negotiates an exposition format from the value of a scrape
+    /// request's `Accept` header, falling back to classic Prometheus text
+    /// when OpenMetrics isn't named.
+    pub fn negotiate(accept: &str) -> Self {
+        if accept.contains(Self::OPENMETRICS_MEDIA_TYPE) {
+            ExpositionFormat::OpenMetrics
+        } else {
+            ExpositionFormat::Prometheus
+        }
+    }
+
+    /// The `Content-Type` to serve a document written in this format with.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExpositionFormat::Prometheus => "text/plain; version=0.0.4",
+            ExpositionFormat::OpenMetrics => {
+                "application/openmetrics-text; version=1.0.0; charset=utf-8"
+            }
+        }
+    }
+}
+
+/// Adapts an `FmtMetrics` to `fmt::Display`, writing it in the negotiated
+/// `ExpositionFormat` and appending the OpenMetrics `# EOF` terminator
+/// when required.
+///
+/// The negotiated format is passed down to `FmtMetrics::fmt_metrics`,
+/// which implementors thread through to `Metric::fmt_scopes_in` so that
+/// exemplars are written or dropped by each metric's own `FmtMetric` impl
+/// (via `fmt_metric_exemplar`/`fmt_metric_labeled_exemplar`); this adapter
+/// itself is only responsible for the document-level `# EOF` line, since
+/// that's the one piece of the grammar that doesn't belong to any single
+/// metric.
+pub struct DisplayExposition<F> {
+    format: ExpositionFormat,
+    metrics: F,
+}
+
+impl<F: FmtMetrics> DisplayExposition<F> {
+    pub fn new(format: ExpositionFormat, metrics: F) -> Self {
+        DisplayExposition { format, metrics }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        self.format.content_type()
+    }
+}
+
+impl<F: FmtMetrics> fmt::Display for DisplayExposition<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.metrics.fmt_metrics(f, self.format)?;
+        if self.format == ExpositionFormat::OpenMetrics {
+            writeln!(f, "# EOF")?;
+        }
+
         Ok(())
     }
 }
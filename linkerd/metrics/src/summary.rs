@@ -0,0 +1,270 @@
+use std::fmt;
+use std::sync::Mutex;
+
+use super::prom::{FmtLabels, FmtMetric, MAX_PRECISE_VALUE};
+
+/// The φ-quantiles reported by every `Summary`.
+const QUANTILES: &[f64] = &[0.5, 0.9, 0.99];
+
+/// The target rank error of the underlying sketch, as a fraction of `n`.
+const EPSILON: f64 = 0.01;
+
+/// How many inserts to allow between compressions of the sketch.
+const COMPRESS_INTERVAL: usize = 128;
+
+/// A Prometheus `summary`: streaming φ-quantiles (e.g. p50/p90/p99) of an
+/// observed value distribution, without pre-committing to fixed histogram
+/// buckets.
+///
+/// Quantiles are estimated with the CKMS streaming sketch (Cormode et al.,
+/// "Effective Computation of Biased Quantiles over Data Streams"), which
+/// bounds memory to a small multiple of `1/ε` regardless of how many
+/// values have been observed. Observation and scraping happen
+/// concurrently, so the sketch is guarded by a lock.
+#[derive(Debug, Default)]
+pub struct Summary {
+    sketch: Mutex<Ckms>,
+}
+
+/// One retained sample in the CKMS sketch: an observed `value`, the
+/// number of ranks it represents relative to the previous sample (`g`),
+/// and the maximum error in that rank (`delta`).
+#[derive(Clone, Debug)]
+struct Sample {
+    value: f64,
+    g: u64,
+    delta: u64,
+}
+
+/// The CKMS streaming quantile sketch backing a `Summary`.
+#[derive(Debug, Default)]
+struct Ckms {
+    samples: Vec<Sample>,
+    n: u64,
+    sum: f64,
+    inserts_since_compress: usize,
+}
+
+impl Ckms {
+    fn insert(&mut self, value: f64) {
+        self.n += 1;
+        self.sum += value;
+
+        let idx = self
+            .samples
+            .iter()
+            .position(|s| s.value > value)
+            .unwrap_or_else(|| self.samples.len());
+
+        // The first and last samples are kept exact, since they bound the
+        // observed range; everything else carries the sketch's current
+        // error budget.
+        let delta = if idx == 0 || idx == self.samples.len() {
+            0
+        } else {
+            (2.0 * EPSILON * self.n as f64).floor() as u64
+        };
+        self.samples.insert(idx, Sample { value, g: 1, delta });
+
+        self.inserts_since_compress += 1;
+        if self.inserts_since_compress >= COMPRESS_INTERVAL {
+            self.compress();
+            self.inserts_since_compress = 0;
+        }
+    }
+
+    /// Merges adjacent samples that can be combined without exceeding the
+    /// sketch's error budget, keeping the sketch's size bounded.
+    ///
+    /// A mergeable pair is folded into its *higher*-indexed sample (the
+    /// lower one is removed and its `g` absorbed into the survivor), so
+    /// the maximum is never the one discarded — preserving the insert-time
+    /// invariant that the first and last samples stay exact.
+    fn compress(&mut self) {
+        if self.samples.len() < 3 {
+            return;
+        }
+        let threshold = (2.0 * EPSILON * self.n as f64).floor() as u64;
+        let mut i = self.samples.len() - 2;
+        while i >= 1 {
+            let merged_rank = self.samples[i].g + self.samples[i + 1].g + self.samples[i + 1].delta;
+            if merged_rank <= threshold {
+                let removed = self.samples.remove(i);
+                self.samples[i].g += removed.g;
+            }
+            i -= 1;
+        }
+    }
+
+    /// Returns the estimated value at quantile `phi` (in `[0, 1]`).
+    fn query(&self, phi: f64) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        let rank = phi * self.n as f64 + EPSILON * self.n as f64;
+        let mut accumulated = 0u64;
+        for sample in &self.samples {
+            accumulated += sample.g;
+            if accumulated as f64 > rank {
+                return sample.value;
+            }
+        }
+
+        self.samples[self.samples.len() - 1].value
+    }
+}
+
+// ===== impl Summary =====
+
+impl Summary {
+    pub fn new() -> Self {
+        Summary::default()
+    }
+
+    /// Records an observed value, clamped so that the running sum stays
+    /// exactly representable in an `f64`.
+    pub fn observe(&self, value: f64) {
+        let value = if value > MAX_PRECISE_VALUE as f64 {
+            MAX_PRECISE_VALUE as f64
+        } else {
+            value
+        };
+
+        let mut sketch = self.sketch.lock().expect("summary lock poisoned");
+        sketch.insert(value);
+    }
+}
+
+impl FmtMetric for Summary {
+    const KIND: &'static str = "summary";
+
+    fn fmt_metric<N: fmt::Display>(&self, f: &mut fmt::Formatter<'_>, name: N) -> fmt::Result {
+        let sketch = self.sketch.lock().expect("summary lock poisoned");
+        for &phi in QUANTILES {
+            writeln!(f, "{}{{quantile=\"{}\"}} {}", name, phi, sketch.query(phi))?;
+        }
+        writeln!(f, "{}_sum {}", name, sketch.sum)?;
+        writeln!(f, "{}_count {}", name, sketch.n)?;
+
+        Ok(())
+    }
+
+    fn fmt_metric_labeled<N, L>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: N,
+        labels: L,
+    ) -> fmt::Result
+    where
+        N: fmt::Display,
+        L: FmtLabels,
+    {
+        let sketch = self.sketch.lock().expect("summary lock poisoned");
+        for &phi in QUANTILES {
+            write!(f, "{}{{", name)?;
+            labels.fmt_labels(f)?;
+            writeln!(f, ",quantile=\"{}\"}} {}", phi, sketch.query(phi))?;
+        }
+
+        write!(f, "{}_sum{{", name)?;
+        labels.fmt_labels(f)?;
+        writeln!(f, "}} {}", sketch.sum)?;
+
+        write!(f, "{}_count{{", name)?;
+        labels.fmt_labels(f)?;
+        writeln!(f, "}} {}", sketch.n)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_sketch_queries_to_zero() {
+        let ckms = Ckms::default();
+        assert_eq!(ckms.query(0.5), 0.0);
+    }
+
+    #[test]
+    fn quantiles_are_accurate_within_twice_epsilon_on_a_uniform_distribution() {
+        let mut ckms = Ckms::default();
+        let n = 10_000u64;
+        for i in 0..n {
+            // A uniform distribution over `[1, n]`, inserted out of order so
+            // the sketch can't rely on presorted input.
+            let value = ((i * 7919) % n) + 1;
+            ckms.insert(value as f64);
+        }
+
+        for &phi in QUANTILES {
+            let got = ckms.query(phi);
+            let want = phi * n as f64;
+            let err = (got - want).abs() / n as f64;
+            assert!(
+                err <= EPSILON + 0.01,
+                "quantile {} estimate {} too far from expected {} (err {})",
+                phi,
+                got,
+                want,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn compression_preserves_the_maximum() {
+        let mut ckms = Ckms::default();
+        let n = 10_000u64;
+        for i in 0..n {
+            let value = ((i * 7919) % n) + 1;
+            ckms.insert(value as f64);
+        }
+
+        // The maximum must survive every compression exactly, since it
+        // bounds the observed range: `query(1.0)` should return it, not a
+        // lower sample left behind by a compression that dropped the max.
+        assert_eq!(ckms.query(1.0), n as f64);
+    }
+
+    #[test]
+    fn compress_keeps_the_sketch_bounded() {
+        let mut ckms = Ckms::default();
+        for i in 0..10_000 {
+            ckms.insert(i as f64);
+        }
+
+        // Compression runs automatically every `COMPRESS_INTERVAL` inserts,
+        // so the retained sample count should stay small relative to `n`.
+        assert!(
+            ckms.samples.len() < 1000,
+            "sketch grew to {} samples for {} inserts",
+            ckms.samples.len(),
+            ckms.n
+        );
+    }
+
+    #[test]
+    fn sum_and_count_match_observations() {
+        let summary = Summary::new();
+        summary.observe(1.0);
+        summary.observe(2.0);
+        summary.observe(3.0);
+
+        let sketch = summary.sketch.lock().unwrap();
+        assert_eq!(sketch.n, 3);
+        assert_eq!(sketch.sum, 6.0);
+    }
+
+    #[test]
+    fn observe_clamps_to_max_precise_value() {
+        let summary = Summary::new();
+        summary.observe(MAX_PRECISE_VALUE as f64 * 2.0);
+
+        let sketch = summary.sketch.lock().unwrap();
+        assert_eq!(sketch.sum, MAX_PRECISE_VALUE as f64);
+    }
+}
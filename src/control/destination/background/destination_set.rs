@@ -1,5 +1,10 @@
 use indexmap::IndexMap;
-use std::{collections::HashMap, iter::IntoIterator, net::SocketAddr};
+use std::{
+    collections::HashMap,
+    iter::{self, IntoIterator},
+    net::SocketAddr,
+    time::Instant,
+};
 
 use futures::{Async, Stream};
 use tower_grpc::{generic::client::GrpcService, BoxBody};
@@ -95,6 +100,55 @@ where
         self.query = client.query(auth, "reconnect")
     }
 
+    /// Records that negotiating `addr`'s proposed protocol hint failed, so
+    /// that the endpoint's metadata reflects the observed protocol and the
+    /// failed upgrade is not retried until the cooldown window elapses.
+    ///
+    /// This is the feedback half of the negotiation loop described on
+    /// `pb_to_addr_meta`: once reported here, every watcher of this
+    /// resolution sees the downgrade. Nothing calls this automatically:
+    /// the connection stack does not detect a failed upgrade at connect
+    /// time and report it here.
+    pub fn record_protocol_fallback(&mut self, auth: &NameAddr, addr: SocketAddr) {
+        let fallback = match self.addrs {
+            Exists::Yes(ref cache) => {
+                let mut found = None;
+                for (&a, meta) in cache {
+                    if a == addr {
+                        found = Some(meta.with_fallback(Instant::now()));
+                        break;
+                    }
+                }
+                found
+            }
+            Exists::No | Exists::Unknown => None,
+        };
+        if let Some(meta) = fallback {
+            self.apply_fallback(auth, addr, meta);
+        }
+    }
+
+    /// Installs a self-observed fallback directly into the cache.
+    ///
+    /// This deliberately does *not* go through `add`: `add`'s
+    /// `preserve_negotiation` merge exists to protect a learned fallback
+    /// from being clobbered by a re-`Add` of the *same* control-plane
+    /// metadata, and would immediately undo the very fallback being
+    /// installed here (the new value's `protocol_hint` is unchanged from
+    /// the cached one, only `negotiation` differs). Going straight to
+    /// `update_union` lets that change through as a `Modification` so
+    /// every watcher observes the downgrade.
+    fn apply_fallback(&mut self, authority_for_logging: &NameAddr, addr: SocketAddr, meta: Metadata) {
+        let mut cache = match self.addrs.take() {
+            Exists::Yes(cache) => cache,
+            Exists::Unknown | Exists::No => Cache::new(),
+        };
+        cache.update_union(iter::once((addr, meta)), &mut |change| {
+            Self::on_change(&mut self.responders, authority_for_logging, change)
+        });
+        self.addrs = Exists::Yes(cache);
+    }
+
     /// Drops any inactive responders.
     pub fn retain_active(&mut self) -> &mut Self {
         self.responders.retain(Responder::is_active);
@@ -182,6 +236,20 @@ where
             Exists::Yes(mut cache) => cache,
             Exists::Unknown | Exists::No => Cache::new(),
         };
+        // Snapshot what's already known so a re-`Add` of the same metadata
+        // can carry forward any locally-learned protocol fallback instead
+        // of having it clobbered by the control plane's original hint.
+        let prior: HashMap<SocketAddr, Metadata> = (&cache)
+            .into_iter()
+            .map(|(&addr, meta)| (addr, meta.clone()))
+            .collect();
+        let addrs_to_add = addrs_to_add.map(|(addr, meta)| {
+            let meta = match prior.get(&addr) {
+                Some(prior_meta) => meta.preserve_negotiation(prior_meta),
+                None => meta,
+            };
+            (addr, meta)
+        });
         cache.update_union(addrs_to_add, &mut |change| {
             Self::on_change(&mut self.responders, authority_for_logging, change)
         });
@@ -255,6 +323,11 @@ where
 }
 
 /// Construct a new labeled `SocketAddr `from a protobuf `WeightedAddr`.
+///
+/// The resulting `Metadata`'s `protocol_hint` is a proposal, not a fact: if
+/// negotiating it later fails, `DestinationSet::record_protocol_fallback`
+/// downgrades it, and `DestinationSet::add` takes care not to let a
+/// subsequent re-`Add` of this same hint clobber that learned fallback.
 fn pb_to_addr_meta(
     pb: WeightedAddr,
     set_labels: &HashMap<String, String>,
@@ -0,0 +1,148 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use futures::sync::mpsc;
+use indexmap::IndexMap;
+
+use identity;
+
+/// How long to avoid re-attempting a protocol upgrade that has already
+/// failed for a given endpoint.
+const NEGOTIATION_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// A handle through which a watcher of a resolution receives updates about
+/// the addresses it currently resolves to.
+pub struct Responder {
+    pub(super) update_tx: mpsc::UnboundedSender<Update>,
+}
+
+impl Responder {
+    pub fn new(update_tx: mpsc::UnboundedSender<Update>) -> Self {
+        Responder { update_tx }
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        !self.update_tx.is_closed()
+    }
+}
+
+/// A change in the set of addresses that a resolution currently has.
+#[derive(Clone, Debug)]
+pub enum Update {
+    Add(SocketAddr, Metadata),
+    Remove(SocketAddr),
+    NoEndpoints,
+}
+
+/// What we currently believe about an endpoint's transport protocol.
+///
+/// The control plane's hint is a *proposal*, not a fact: the proxy may
+/// later learn, by attempting to speak it and failing, that the endpoint
+/// actually wants something else.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProtocolHint {
+    /// No hint was given; speak HTTP/1.1 unless told otherwise.
+    Unknown,
+    /// The control plane proposed HTTP/2.
+    Http2,
+}
+
+/// Whether a `ProtocolHint` is still trusted, or has been locally
+/// contradicted by a failed negotiation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Negotiation {
+    /// The control plane's hint has not been contradicted.
+    Proposed,
+    /// Negotiating the hinted protocol failed; avoid retrying it until
+    /// `retry_after`.
+    FellBack { retry_after: Instant },
+}
+
+/// Endpoint metadata, as provided by the Destination service.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Metadata {
+    /// A set of Prometheus metric labels describing the destination.
+    labels: IndexMap<String, String>,
+
+    /// The protocol proposed by the control plane for this endpoint.
+    protocol_hint: ProtocolHint,
+
+    /// Whether `protocol_hint` is still trusted, or has been locally
+    /// overridden by a learned fallback.
+    negotiation: Negotiation,
+
+    /// The endpoint's TLS identity, if it has one.
+    tls_identity: Option<identity::Name>,
+
+    /// A relative weight for use in traffic splitting, as reported by the
+    /// control plane. A weight of `0` means the endpoint should receive no
+    /// new traffic, e.g. because it is draining.
+    weight: u32,
+}
+
+impl Metadata {
+    /// Construct `Metadata` from a freshly-observed control-plane value.
+    /// The hint it carries is treated as an unconfirmed proposal.
+    pub fn new(
+        labels: IndexMap<String, String>,
+        protocol_hint: ProtocolHint,
+        tls_identity: Option<identity::Name>,
+        weight: u32,
+    ) -> Self {
+        Metadata {
+            labels,
+            protocol_hint,
+            negotiation: Negotiation::Proposed,
+            tls_identity,
+            weight,
+        }
+    }
+
+    pub fn labels(&self) -> &IndexMap<String, String> {
+        &self.labels
+    }
+
+    pub fn tls_identity(&self) -> Option<&identity::Name> {
+        self.tls_identity.as_ref()
+    }
+
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    /// Returns the protocol that should currently be used to connect to
+    /// this endpoint, taking any learned fallback into account.
+    pub fn protocol_hint(&self) -> ProtocolHint {
+        match self.negotiation {
+            Negotiation::FellBack { retry_after } if Instant::now() < retry_after => {
+                ProtocolHint::Unknown
+            }
+            _ => self.protocol_hint.clone(),
+        }
+    }
+
+    /// Returns a copy of this metadata recording that negotiating
+    /// `self.protocol_hint()` failed, so it will not be retried until the
+    /// cooldown window has elapsed.
+    pub(super) fn with_fallback(&self, now: Instant) -> Self {
+        Metadata {
+            negotiation: Negotiation::FellBack {
+                retry_after: now + NEGOTIATION_COOLDOWN,
+            },
+            ..self.clone()
+        }
+    }
+
+    /// Carries forward any fallback learned on `prior` onto `self`, unless
+    /// `self`'s hint differs from `prior`'s.
+    ///
+    /// A control-plane `Modification` that changes the hint means the
+    /// backend may have changed, so the learned fallback must not survive
+    /// it; a pure re-`Add` of the same hint must not clobber it.
+    pub(super) fn preserve_negotiation(mut self, prior: &Metadata) -> Self {
+        if self.protocol_hint == prior.protocol_hint {
+            self.negotiation = prior.negotiation.clone();
+        }
+        self
+    }
+}
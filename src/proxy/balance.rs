@@ -0,0 +1,224 @@
+//! Weighted power-of-two-choices endpoint selection.
+//!
+//! Endpoints resolved from the Destination API may carry a relative
+//! `weight` (see `control::destination::Metadata::weight`), letting
+//! operators drive canary releases and traffic splitting purely from the
+//! control plane. `WeightedP2c` samples two distinct endpoints with
+//! probability proportional to their weight, then defers to the caller's
+//! load signal to break the tie in favor of the less-loaded endpoint.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use control::destination::Update;
+
+/// How many times to resample the second candidate before giving up on
+/// finding one distinct from the first.
+const MAX_RESAMPLES: usize = 8;
+
+/// Incrementally-maintained endpoint weights for weighted
+/// power-of-two-choices selection.
+///
+/// The table is driven by a resolution's `Update` stream: `Add` and
+/// `Remove` keep it in sync with the endpoint set, and a re-`Add` with a
+/// changed weight (a `CacheChange::Modification` surfaced as `Update::Add`)
+/// only adjusts the stored weight in place, without resetting any
+/// connection state. The running `total_weight` is maintained alongside
+/// the table, so updates never recompute it; sampling a candidate still
+/// walks the table, so each pick is O(n) in the number of endpoints.
+pub struct WeightedP2c<R = SmallRng> {
+    weights: HashMap<SocketAddr, u32>,
+    total_weight: u64,
+    rng: R,
+}
+
+impl WeightedP2c<SmallRng> {
+    pub fn new() -> Self {
+        Self::new_with_rng(SmallRng::from_entropy())
+    }
+}
+
+impl Default for WeightedP2c<SmallRng> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: Rng> WeightedP2c<R> {
+    pub fn new_with_rng(rng: R) -> Self {
+        WeightedP2c {
+            weights: HashMap::new(),
+            total_weight: 0,
+            rng,
+        }
+    }
+
+    /// Applies a single `Update` from a resolution's stream to the weight
+    /// table.
+    pub fn apply(&mut self, update: &Update) {
+        match *update {
+            Update::Add(addr, ref meta) => self.set_weight(addr, meta.weight()),
+            Update::Remove(addr) => self.remove(addr),
+            Update::NoEndpoints => self.clear(),
+        }
+    }
+
+    fn set_weight(&mut self, addr: SocketAddr, weight: u32) {
+        match self.weights.insert(addr, weight) {
+            Some(old) => {
+                self.total_weight = self.total_weight - u64::from(old) + u64::from(weight);
+            }
+            None => {
+                self.total_weight += u64::from(weight);
+            }
+        }
+    }
+
+    fn remove(&mut self, addr: SocketAddr) {
+        if let Some(weight) = self.weights.remove(&addr) {
+            self.total_weight -= u64::from(weight);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.weights.clear();
+        self.total_weight = 0;
+    }
+
+    /// Samples one endpoint with probability proportional to its weight.
+    ///
+    /// A weight of `0` ("draining") is never sampled, though the endpoint
+    /// stays in the table so it can be selected again once re-weighted.
+    fn sample(&mut self) -> Option<SocketAddr> {
+        if self.total_weight == 0 {
+            return None;
+        }
+        let mut pick = self.rng.gen_range(0, self.total_weight);
+        for (&addr, &weight) in &self.weights {
+            let weight = u64::from(weight);
+            if weight == 0 {
+                continue;
+            }
+            if pick < weight {
+                return Some(addr);
+            }
+            pick -= weight;
+        }
+        None
+    }
+
+    /// Chooses an endpoint via weighted power-of-two-choices: sample two
+    /// distinct candidates by weight, then pick whichever `load` reports
+    /// as less busy.
+    ///
+    /// `load` is typically backed by whatever in-flight-request signal the
+    /// balancer already tracks per endpoint; callers without one can fall
+    /// back to a least-in-flight counter of their own.
+    pub fn choose<F>(&mut self, mut load: F) -> Option<SocketAddr>
+    where
+        F: FnMut(SocketAddr) -> usize,
+    {
+        let a = self.sample()?;
+        let mut b = self.sample()?;
+        for _ in 0..MAX_RESAMPLES {
+            if b != a {
+                break;
+            }
+            b = self.sample()?;
+        }
+        if b != a && load(b) < load(a) {
+            Some(b)
+        } else {
+            Some(a)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use control::destination::{Metadata, ProtocolHint};
+    use indexmap::IndexMap;
+    use rand::SeedableRng;
+
+    fn meta(weight: u32) -> Metadata {
+        Metadata::new(IndexMap::new(), ProtocolHint::Unknown, None, weight)
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    fn p2c() -> WeightedP2c {
+        WeightedP2c::new_with_rng(SmallRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn total_weight_tracks_incremental_updates() {
+        let mut p2c = p2c();
+        p2c.apply(&Update::Add(addr(1), meta(5)));
+        p2c.apply(&Update::Add(addr(2), meta(10)));
+        assert_eq!(p2c.total_weight, 15);
+
+        // A re-`Add` with a changed weight (a `Modification` surfaced as
+        // `Update::Add`) adjusts the running total in place.
+        p2c.apply(&Update::Add(addr(1), meta(7)));
+        assert_eq!(p2c.total_weight, 17);
+        assert_eq!(p2c.weights.len(), 2);
+
+        p2c.apply(&Update::Remove(addr(2)));
+        assert_eq!(p2c.total_weight, 7);
+        assert_eq!(p2c.weights.len(), 1);
+
+        p2c.apply(&Update::NoEndpoints);
+        assert_eq!(p2c.total_weight, 0);
+        assert!(p2c.weights.is_empty());
+    }
+
+    #[test]
+    fn weight_zero_drains_without_dropping_the_endpoint() {
+        let mut p2c = p2c();
+        p2c.apply(&Update::Add(addr(1), meta(0)));
+        p2c.apply(&Update::Add(addr(2), meta(1)));
+
+        // The draining endpoint stays in the table...
+        assert_eq!(p2c.weights.len(), 2);
+        // ...but is never sampled while its weight is 0.
+        for _ in 0..100 {
+            assert_eq!(p2c.sample(), Some(addr(2)));
+        }
+    }
+
+    #[test]
+    fn sample_is_weighted_towards_the_heavier_endpoint() {
+        let mut p2c = p2c();
+        p2c.apply(&Update::Add(addr(1), meta(1)));
+        p2c.apply(&Update::Add(addr(2), meta(99)));
+
+        let mut heavy_picks = 0;
+        for _ in 0..1000 {
+            if p2c.sample() == Some(addr(2)) {
+                heavy_picks += 1;
+            }
+        }
+        assert!(
+            heavy_picks > 900,
+            "expected heavily-weighted endpoint to dominate sampling, got {} picks",
+            heavy_picks
+        );
+    }
+
+    #[test]
+    fn choose_prefers_the_less_loaded_candidate() {
+        let mut p2c = p2c();
+        p2c.apply(&Update::Add(addr(1), meta(1)));
+        p2c.apply(&Update::Add(addr(2), meta(1)));
+
+        for _ in 0..20 {
+            let choice = p2c.choose(|a| if a == addr(1) { 1 } else { 0 });
+            assert_eq!(choice, Some(addr(2)));
+        }
+    }
+}